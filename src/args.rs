@@ -1,8 +1,9 @@
 use crate::proxy::Endpoint as ProxyEndpoint;
 
-use std::{borrow::Cow, net::ToSocketAddrs, str::FromStr};
+use std::{borrow::Cow, net::ToSocketAddrs, path::PathBuf, str::FromStr};
 
 use clap::{AppSettings::DeriveDisplayOrder, Parser};
+use serde::Deserialize;
 
 #[cfg(unix)]
 fn shell() -> &'static str {
@@ -34,17 +35,39 @@ pub struct Args {
     /// Directives are specified `<SOURCE><><DESTINATION>`,
     /// where connections to `<SOURCE>` will be proxied to `<DESTINATION>`.
     /// Both sides are specified as `[<TYPE>:]<ADDRESS>`,
-    /// where type can be `tcp`, `unix` or `pipe`.
+    /// where type can be `tcp`, `unix`, `pipe`, `cmd` or `exec`.
     /// If `<TYPE>` is omitted, `tcp` is assumed.
     /// `<ADDRESS>` should be specified as `<HOST>:<PORT>` for `tcp`,
-    /// as a valid file path for `unix` and as a valid pipe name for `pipe`.
+    /// as a valid file path for `unix`, as a valid pipe name for `pipe`
+    /// and as a shell command for `cmd` and `exec`.
+    /// A `cmd` endpoint can only be used as a destination, where each accepted
+    /// connection is piped through a freshly spawned process' standard streams.
+    /// An `exec` endpoint is likewise a destination only and also spawns the
+    /// command for each accepted connection in an inetd fashion, attaching the
+    /// connection to the child's standard input and output and waiting for the
+    /// child to exit once both directions are closed.
+    /// When built with the `vsock` feature, `vsock:<CID>:<PORT>` is also accepted,
+    /// where `<CID>` may be a number or one of `host`, `local` or `any`.
+    /// When built with the `ws` feature, `ws:<HOST>:<PORT>/<PATH>` and its
+    /// `wss` variant bridge a connection over a WebSocket, either exposing a
+    /// service to WebSocket clients or dialing a remote WebSocket server.
+    /// A `wss` endpoint can only be used as a destination.
     ///
     /// Example: `[::]:80<>localhost:8080`
     /// {n}Example: `tcp:localhost:5432<>unix:/var/run/postgresql/.s.PGSQL.5432`
     /// {n}Example: `tcp:localhost:2375<>pipe:\\.\pipe\docker_engine`
+    /// {n}Example: `tcp:[::]:25<>cmd:openssl s_client -connect example.com:465`
     #[clap(short, long = "proxy")]
     pub proxies: Vec<ProxyDirective>,
 
+    /// Load options from a TOML configuration file
+    ///
+    /// Proxies, commands, the shell and the buffered flag may be specified in a
+    /// TOML file instead of on the command line. Any option given on the command
+    /// line takes precedence over the file.
+    #[clap(short, long)]
+    pub config: Option<PathBuf>,
+
     /// Shell to use
     ///
     /// Commands will be passed to this shell as a single argument,
@@ -61,6 +84,19 @@ pub struct Args {
     #[cfg_attr(unix, clap(default_value = "-c"))]
     pub shell_args: Vec<String>,
 
+    /// Maximum number of attempts when connecting to a proxy destination
+    ///
+    /// Transient failures such as a refused connection or a missing socket,
+    /// which typically mean the destination is still starting up, are retried
+    /// with an exponential backoff until this many attempts have been made in
+    /// all, after which the client is dropped.
+    #[clap(long, default_value_t = 10)]
+    pub connect_attempts: u32,
+
+    /// Maximum delay between destination connection attempts, in milliseconds
+    #[clap(long, default_value_t = 1000)]
+    pub connect_max_delay: u64,
+
     /// Buffer output at newlines
     ///
     /// By default, commands inherit standard output and error streams.
@@ -83,15 +119,89 @@ pub struct Args {
 }
 
 pub fn parse() -> Args {
-    Args::parse()
+    let mut args = Args::parse();
+
+    if let Some(path) = args.config.take() {
+        let contents = std::fs::read_to_string(&path).unwrap_or_else(|err| {
+            eprintln!("Could not read config file `{}`: {err}", path.display());
+            std::process::exit(1);
+        });
+        let config: Config = toml::from_str(&contents).unwrap_or_else(|err| {
+            eprintln!("Could not parse config file `{}`: {err}", path.display());
+            std::process::exit(1);
+        });
+        args.merge(config);
+    }
+
+    args
+}
+
+impl Args {
+    /// Fill in any option left unset on the command line from `config`.
+    /// Command line values always win.
+    fn merge(&mut self, config: Config) {
+        if self.proxies.is_empty() {
+            self.proxies = config.proxies;
+        }
+        if self.commands.is_empty() {
+            self.commands = config.commands;
+        }
+        if self.raw_commands.is_empty() {
+            self.raw_commands = config.raw_commands;
+        }
+        if self.shell == shell() {
+            if let Some(shell) = config.shell {
+                self.shell = shell;
+            }
+        }
+        if self.shell_args == default_shell_args() && !config.shell_args.is_empty() {
+            self.shell_args = config.shell_args;
+        }
+        self.buffered |= config.buffered;
+    }
+}
+
+fn default_shell_args() -> Vec<String> {
+    #[cfg(unix)]
+    {
+        vec![String::from("-c")]
+    }
+    #[cfg(windows)]
+    {
+        Vec::new()
+    }
+}
+
+/// TOML counterpart of [`Args`], allowing a whole environment to be committed
+/// to a `pxx.toml` instead of a sprawling command line.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    pub proxies: Vec<ProxyDirective>,
+    pub shell: Option<String>,
+    pub shell_args: Vec<String>,
+    pub buffered: bool,
+    pub raw_commands: Vec<String>,
+    pub commands: Vec<String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
 pub struct ProxyDirective {
+    #[serde(deserialize_with = "de_endpoint")]
     pub source: ProxyEndpoint,
+    #[serde(deserialize_with = "de_endpoint")]
     pub destination: ProxyEndpoint,
 }
 
+fn de_endpoint<'de, D>(deserializer: D) -> Result<ProxyEndpoint, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(Error::custom)
+}
+
 impl FromStr for ProxyDirective {
     type Err = Cow<'static, str>;
 
@@ -124,6 +234,25 @@ impl FromStr for ProxyEndpoint {
             ))
         };
 
+        #[cfg(feature = "vsock")]
+        let vsock = |endpoint: &str| -> Result<Self, Self::Err> {
+            let (cid, port) = endpoint
+                .split_once(':')
+                .ok_or("vsock endpoints must be of the form `vsock:<CID>:<PORT>`")?;
+            let cid = match cid {
+                "host" => 2,
+                "local" => 1,
+                "any" => u32::MAX,
+                _ => cid
+                    .parse()
+                    .map_err(|err| format!("Invalid vsock CID `{cid}`: {err}"))?,
+            };
+            let port = port
+                .parse()
+                .map_err(|err| format!("Invalid vsock port `{port}`: {err}"))?;
+            Ok(Self::Vsock { cid, port })
+        };
+
         match s.split_once(':') {
             Some(("tcp", endpoint)) => tcp(endpoint),
             #[cfg(unix)]
@@ -134,6 +263,22 @@ impl FromStr for ProxyEndpoint {
             Some(("pipe", path)) => Ok(Self::Pipe(std::ffi::OsString::from(path))),
             #[cfg(unix)]
             Some(("pipe", _)) => Err("Named pipes are not supported on Unix".into()),
+            Some(("cmd", command)) => Ok(Self::Command(command.to_owned())),
+            Some(("exec", command)) => Ok(Self::Exec(command.to_owned())),
+            #[cfg(feature = "vsock")]
+            Some(("vsock", endpoint)) => vsock(endpoint),
+            #[cfg(not(feature = "vsock"))]
+            Some(("vsock", _)) => {
+                Err("vsock support is not enabled; rebuild with the `vsock` feature".into())
+            }
+            #[cfg(feature = "ws")]
+            Some(("ws", addr)) => Ok(Self::Ws(addr.to_owned())),
+            #[cfg(feature = "ws")]
+            Some(("wss", addr)) => Ok(Self::Wss(addr.to_owned())),
+            #[cfg(not(feature = "ws"))]
+            Some(("ws" | "wss", _)) => {
+                Err("WebSocket support is not enabled; rebuild with the `ws` feature".into())
+            }
             _ => tcp(s),
         }
     }
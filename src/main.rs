@@ -63,11 +63,17 @@ async fn future(args: Args) -> io::Result<i32> {
         shell,
         shell_args,
         buffered,
+        connect_attempts,
+        connect_max_delay,
         ..
     } = args;
 
+    let backoff = proxy::Backoff {
+        max_attempts: connect_attempts,
+        max_delay: std::time::Duration::from_millis(connect_max_delay),
+    };
     for proxy in proxies {
-        tokio::spawn(proxy::proxy(proxy.source, proxy.destination));
+        tokio::spawn(proxy::proxy(proxy.source, proxy.destination, backoff));
     }
 
     let buffers = if buffered {
@@ -4,12 +4,16 @@ use std::{
     net::SocketAddr,
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
+#[cfg(feature = "ws")]
+use std::net::ToSocketAddrs;
 
 use pin_project::pin_project;
 use tokio::{
     io::{AsyncRead, AsyncWrite, ReadBuf},
     net::{TcpListener, TcpStream},
+    process::{Child, ChildStdin, ChildStdout},
 };
 
 #[derive(Debug, Clone)]
@@ -19,9 +23,25 @@ pub enum Endpoint {
     Unix(std::path::PathBuf),
     #[cfg(windows)]
     Pipe(std::ffi::OsString),
+    Command(String),
+    Exec(String),
+    #[cfg(feature = "vsock")]
+    Vsock { cid: u32, port: u32 },
+    #[cfg(feature = "ws")]
+    Ws(String),
+    #[cfg(feature = "ws")]
+    Wss(String),
 }
 
-pub async fn proxy(source: Endpoint, destination: Endpoint) -> io::Result<()> {
+/// Tuning for how hard [`proxy`] retries a destination connection before
+/// giving up on an accepted client.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    pub max_attempts: u32,
+    pub max_delay: Duration,
+}
+
+pub async fn proxy(source: Endpoint, destination: Endpoint, backoff: Backoff) -> io::Result<()> {
     let mut listener = Listener::bind(source).await?;
 
     loop {
@@ -29,14 +49,59 @@ pub async fn proxy(source: Endpoint, destination: Endpoint) -> io::Result<()> {
         let destination = destination.clone();
 
         tokio::spawn(async move {
-            let mut destination = Stream::connect(destination).await.unwrap();
-            tokio::io::copy_bidirectional(&mut source, &mut destination)
-                .await
-                .unwrap()
+            if let Endpoint::Exec(command) = &destination {
+                if let Err(err) = exec(command, source).await {
+                    eprintln!("`exec:{command}` failed: {err}");
+                }
+                return;
+            }
+
+            let mut stream = match Stream::connect_with_backoff(&destination, backoff).await {
+                Ok(stream) => stream,
+                Err(err) => {
+                    eprintln!("Could not connect to `{destination}`: {err}");
+                    return;
+                }
+            };
+            if let Err(err) = tokio::io::copy_bidirectional(&mut source, &mut stream).await {
+                eprintln!("Connection to `{destination}` failed: {err}");
+            }
         });
     }
 }
 
+/// Spawn `command` for a single accepted connection, wiring the socket's read
+/// half to the child's stdin and the child's stdout back to the socket, then
+/// reaping the child once both directions reach EOF (inetd-style).
+async fn exec(command: &str, source: Stream) -> io::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut child = spawn_command(command)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| io::Error::new(err.kind(), format!("Could not spawn `{command}`: {err}")))?;
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+
+    let (mut reader, mut writer) = tokio::io::split(source);
+    // `stdin` is moved in so the pipe is closed, and the child sees EOF, as
+    // soon as this direction is done; shutting it down alone does not close it
+    let to_child = async move {
+        tokio::io::copy(&mut reader, &mut stdin).await?;
+        stdin.shutdown().await
+    };
+    let from_child = async {
+        tokio::io::copy(&mut stdout, &mut writer).await?;
+        writer.shutdown().await
+    };
+    tokio::try_join!(to_child, from_child)?;
+
+    child.wait().await?;
+    Ok(())
+}
+
 #[pin_project(project = StreamProjection)]
 enum Stream {
     Tcp(#[pin] TcpStream),
@@ -46,6 +111,19 @@ enum Stream {
     PipeClient(#[pin] tokio::net::windows::named_pipe::NamedPipeClient),
     #[cfg(windows)]
     PipeServer(#[pin] tokio::net::windows::named_pipe::NamedPipeServer),
+    Child {
+        // kept so `kill_on_drop` kills the process when the stream is dropped;
+        // shutting down or reaching EOF on one half does not kill it
+        _child: Child,
+        #[pin]
+        stdout: ChildStdout,
+        #[pin]
+        stdin: ChildStdin,
+    },
+    #[cfg(feature = "vsock")]
+    Vsock(#[pin] tokio_vsock::VsockStream),
+    #[cfg(feature = "ws")]
+    Ws(#[pin] WsStream),
 }
 
 enum Listener {
@@ -57,6 +135,10 @@ enum Listener {
         server: tokio::net::windows::named_pipe::NamedPipeServer,
         name: std::ffi::OsString,
     },
+    #[cfg(feature = "vsock")]
+    Vsock(tokio_vsock::VsockListener),
+    #[cfg(feature = "ws")]
+    Ws(TcpListener),
 }
 
 impl Stream {
@@ -67,25 +149,90 @@ impl Stream {
             Endpoint::Unix(path) => Self::Unix(tokio::net::UnixStream::connect(path).await?),
             #[cfg(windows)]
             Endpoint::Pipe(name) => {
-                let mut wait = 0;
-                loop {
-                    match tokio::net::windows::named_pipe::ClientOptions::new().open(&name) {
-                        Ok(client) => break Self::PipeClient(client),
-                        Err(err) if err.raw_os_error() == Some(231) => {
-                            if wait == 0 {
-                                tokio::task::yield_now().await;
-                                wait = 1;
-                            } else {
-                                tokio::time::sleep(tokio::time::Duration::from_millis(wait)).await;
-                                wait *= 2;
-                            }
-                        }
-                        Err(err) => return Err(err),
-                    }
+                Self::PipeClient(tokio::net::windows::named_pipe::ClientOptions::new().open(&name)?)
+            }
+            Endpoint::Command(command) => {
+                let mut child = spawn_command(&command)
+                    .stdin(std::process::Stdio::piped())
+                    .stdout(std::process::Stdio::piped())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .map_err(|err| {
+                        io::Error::new(err.kind(), format!("Could not spawn `{command}`: {err}"))
+                    })?;
+                let stdout = child.stdout.take().unwrap();
+                let stdin = child.stdin.take().unwrap();
+                Self::Child {
+                    _child: child,
+                    stdout,
+                    stdin,
                 }
             }
+            Endpoint::Exec(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "`exec` endpoints are spawned per connection, not dialed",
+                ))
+            }
+            #[cfg(feature = "vsock")]
+            Endpoint::Vsock { cid, port } => Self::Vsock(
+                tokio_vsock::VsockStream::connect(tokio_vsock::VsockAddr::new(cid, port)).await?,
+            ),
+            #[cfg(feature = "ws")]
+            Endpoint::Ws(addr) => Self::Ws(WsStream::connect(false, &addr).await?),
+            #[cfg(feature = "ws")]
+            Endpoint::Wss(addr) => Self::Ws(WsStream::connect(true, &addr).await?),
         })
     }
+
+    /// Connect to `endpoint`, retrying transient failures with an exponential
+    /// backoff so the downstream service can still be coming up.
+    ///
+    /// The first retry simply yields to the runtime, after which the delay
+    /// starts at 1ms and doubles each attempt up to `backoff.max_delay`.
+    async fn connect_with_backoff(endpoint: &Endpoint, backoff: Backoff) -> io::Result<Self> {
+        let max_attempts = backoff.max_attempts.max(1);
+        let mut wait = 0;
+        for attempt in 1..=max_attempts {
+            match Self::connect(endpoint.clone()).await {
+                Ok(stream) => return Ok(stream),
+                Err(err) if attempt < max_attempts && is_transient(&err) => {
+                    if wait == 0 {
+                        tokio::task::yield_now().await;
+                        wait = 1;
+                    } else {
+                        tokio::time::sleep(Duration::from_millis(wait).min(backoff.max_delay))
+                            .await;
+                        wait = wait.saturating_mul(2);
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        unreachable!("max_attempts is at least 1")
+    }
+}
+
+/// Whether a failed connection is worth retrying, i.e. the destination is
+/// likely still starting up rather than misconfigured.
+fn is_transient(err: &io::Error) -> bool {
+    use io::ErrorKind::*;
+    matches!(err.kind(), ConnectionRefused | NotFound)
+        // ERROR_PIPE_BUSY: the named pipe exists but has no free instance yet
+        || (cfg!(windows) && err.raw_os_error() == Some(231))
+}
+
+#[cfg(unix)]
+fn spawn_command(command: &str) -> tokio::process::Command {
+    let mut cmd = tokio::process::Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+#[cfg(windows)]
+fn spawn_command(command: &str) -> tokio::process::Command {
+    let mut cmd = tokio::process::Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
 }
 
 impl Listener {
@@ -101,6 +248,33 @@ impl Listener {
                     .create(&path)?,
                 name: path,
             },
+            Endpoint::Command(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "`cmd` endpoints can only be used as a proxy destination",
+                ))
+            }
+            Endpoint::Exec(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "`exec` endpoints can only be used as a proxy destination",
+                ))
+            }
+            #[cfg(feature = "vsock")]
+            Endpoint::Vsock { cid, port } => {
+                Self::Vsock(tokio_vsock::VsockListener::bind(tokio_vsock::VsockAddr::new(
+                    cid, port,
+                ))?)
+            }
+            #[cfg(feature = "ws")]
+            Endpoint::Ws(addr) => Self::Ws(TcpListener::bind(ws_listen_addr(&addr)?).await?),
+            #[cfg(feature = "ws")]
+            Endpoint::Wss(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "`wss` listeners are not supported; terminate TLS in front of a `ws` listener",
+                ))
+            }
         })
     }
 
@@ -118,6 +292,17 @@ impl Listener {
                 );
                 Stream::PipeServer(stream)
             }
+            #[cfg(feature = "vsock")]
+            Self::Vsock(listener) => Stream::Vsock(listener.accept().await?.0),
+            #[cfg(feature = "ws")]
+            Self::Ws(listener) => {
+                let (stream, _) = listener.accept().await?;
+                let ws =
+                    tokio_tungstenite::accept_async(tokio_tungstenite::MaybeTlsStream::Plain(stream))
+                        .await
+                        .map_err(ws_err)?;
+                Stream::Ws(WsStream::new(ws))
+            }
         })
     }
 }
@@ -130,12 +315,20 @@ impl Display for Endpoint {
             Endpoint::Unix(path) => write!(f, "unix:{}", path.display()),
             #[cfg(windows)]
             Endpoint::Pipe(name) => write!(f, "pipe:{}", name.to_string_lossy()),
+            Endpoint::Command(command) => write!(f, "cmd:{command}"),
+            Endpoint::Exec(command) => write!(f, "exec:{command}"),
+            #[cfg(feature = "vsock")]
+            Endpoint::Vsock { cid, port } => write!(f, "vsock:{cid}:{port}"),
+            #[cfg(feature = "ws")]
+            Endpoint::Ws(addr) => write!(f, "ws:{addr}"),
+            #[cfg(feature = "ws")]
+            Endpoint::Wss(addr) => write!(f, "wss:{addr}"),
         }
     }
 }
 
 macro_rules! project_stream {
-    ($self:expr; $inner:ident => $e:expr) => {
+    ($self:expr, $half:ident; $inner:ident => $e:expr) => {
         match $self.project() {
             StreamProjection::Tcp($inner) => $e,
             #[cfg(unix)]
@@ -144,6 +337,11 @@ macro_rules! project_stream {
             StreamProjection::PipeClient($inner) => $e,
             #[cfg(windows)]
             StreamProjection::PipeServer($inner) => $e,
+            StreamProjection::Child { $half: $inner, .. } => $e,
+            #[cfg(feature = "vsock")]
+            StreamProjection::Vsock($inner) => $e,
+            #[cfg(feature = "ws")]
+            StreamProjection::Ws($inner) => $e,
         }
     };
 }
@@ -154,7 +352,7 @@ impl AsyncRead for Stream {
         cx: &mut Context<'_>,
         buf: &mut ReadBuf<'_>,
     ) -> Poll<io::Result<()>> {
-        project_stream!(self; stream => stream.poll_read(cx, buf))
+        project_stream!(self, stdout; stream => stream.poll_read(cx, buf))
     }
 }
 
@@ -164,15 +362,15 @@ impl AsyncWrite for Stream {
         cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
-        project_stream!(self; stream => stream.poll_write(cx, buf))
+        project_stream!(self, stdin; stream => stream.poll_write(cx, buf))
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
-        project_stream!(self; stream => stream.poll_flush(cx))
+        project_stream!(self, stdin; stream => stream.poll_flush(cx))
     }
 
     fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
-        project_stream!(self; stream => stream.poll_shutdown(cx))
+        project_stream!(self, stdin; stream => stream.poll_shutdown(cx))
     }
 
     fn poll_write_vectored(
@@ -180,7 +378,7 @@ impl AsyncWrite for Stream {
         cx: &mut Context<'_>,
         bufs: &[io::IoSlice<'_>],
     ) -> Poll<Result<usize, io::Error>> {
-        project_stream!(self; stream => stream.poll_write_vectored(cx, bufs))
+        project_stream!(self, stdin; stream => stream.poll_write_vectored(cx, bufs))
     }
 
     fn is_write_vectored(&self) -> bool {
@@ -192,6 +390,132 @@ impl AsyncWrite for Stream {
             Stream::PipeClient(client) => client.is_write_vectored(),
             #[cfg(windows)]
             Stream::PipeServer(server) => server.is_write_vectored(),
+            Stream::Child { stdin, .. } => stdin.is_write_vectored(),
+            #[cfg(feature = "vsock")]
+            Stream::Vsock(stream) => stream.is_write_vectored(),
+            #[cfg(feature = "ws")]
+            Stream::Ws(_) => false,
         }
     }
 }
+
+#[cfg(feature = "ws")]
+mod ws {
+    use super::*;
+
+    use futures_util::{Sink, Stream as _};
+    use tokio_tungstenite::{
+        tungstenite::{Error as WsError, Message},
+        MaybeTlsStream, WebSocketStream,
+    };
+
+    type Inner = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    /// Adapts a WebSocket connection to an `AsyncRead`/`AsyncWrite` byte stream
+    /// by carrying the payload in binary frames. Ping/pong frames are handled by
+    /// the protocol layer and text frames are refused.
+    #[pin_project]
+    pub struct WsStream {
+        #[pin]
+        inner: Inner,
+        read_buf: Vec<u8>,
+    }
+
+    impl WsStream {
+        pub fn new(inner: Inner) -> Self {
+            Self {
+                inner,
+                read_buf: Vec::new(),
+            }
+        }
+
+        pub async fn connect(secure: bool, addr: &str) -> io::Result<Self> {
+            let scheme = if secure { "wss" } else { "ws" };
+            let (inner, _) = tokio_tungstenite::connect_async(format!("{scheme}://{addr}"))
+                .await
+                .map_err(ws_err)?;
+            Ok(Self::new(inner))
+        }
+    }
+
+    impl AsyncRead for WsStream {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let mut this = self.project();
+            loop {
+                if !this.read_buf.is_empty() {
+                    let n = this.read_buf.len().min(buf.remaining());
+                    buf.put_slice(&this.read_buf[..n]);
+                    this.read_buf.drain(..n);
+                    return Poll::Ready(Ok(()));
+                }
+
+                match this.inner.as_mut().poll_next(cx) {
+                    Poll::Ready(Some(Ok(Message::Binary(data)))) => *this.read_buf = data,
+                    // Pings are answered by the protocol layer, pongs are informational.
+                    Poll::Ready(Some(Ok(Message::Ping(_) | Message::Pong(_)))) => continue,
+                    // A clean or exhausted stream is end-of-file.
+                    Poll::Ready(Some(Ok(Message::Close(_))) | None) => return Poll::Ready(Ok(())),
+                    Poll::Ready(Some(Ok(Message::Text(_)))) => {
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "unexpected text frame on a binary WebSocket stream",
+                        )))
+                    }
+                    Poll::Ready(Some(Ok(Message::Frame(_)))) => continue,
+                    Poll::Ready(Some(Err(err))) => return Poll::Ready(Err(ws_err(err))),
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+        }
+    }
+
+    impl AsyncWrite for WsStream {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let mut this = self.project();
+            match this.inner.as_mut().poll_ready(cx) {
+                Poll::Ready(Ok(())) => match this.inner.start_send(Message::Binary(buf.to_vec())) {
+                    Ok(()) => Poll::Ready(Ok(buf.len())),
+                    Err(err) => Poll::Ready(Err(ws_err(err))),
+                },
+                Poll::Ready(Err(err)) => Poll::Ready(Err(ws_err(err))),
+                Poll::Pending => Poll::Pending,
+            }
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.project().inner.poll_flush(cx).map_err(ws_err)
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.project().inner.poll_close(cx).map_err(ws_err)
+        }
+    }
+
+    pub fn ws_err(err: WsError) -> io::Error {
+        io::Error::new(io::ErrorKind::Other, err)
+    }
+
+    pub fn ws_listen_addr(addr: &str) -> io::Result<SocketAddr> {
+        let authority = addr.split('/').next().unwrap_or(addr);
+        authority
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("no addresses found for `{authority}`"),
+                )
+            })
+    }
+}
+
+#[cfg(feature = "ws")]
+use ws::{ws_err, ws_listen_addr, WsStream};